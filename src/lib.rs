@@ -2,11 +2,194 @@
 #[macro_use]
 extern crate approx;
 extern crate num;
+extern crate rand;
 
-use num::{Integer, PrimInt, Unsigned};
+#[cfg(feature = "bnum")]
+extern crate bnum;
 
+use num::{PrimInt, Unsigned};
+use rand::{Rng, RngCore};
+
+use std::convert::TryFrom;
 use std::mem::size_of;
-use std::ops::{Shl, Shr};
+
+/// Bit-level operations `compress_int` and friends need from an unsigned
+/// integer type, independent of its width.
+///
+/// The built-in unsigned types implement this directly. `T: Into<u32>`,
+/// which the compression functions used to require, is wrong for `u64`
+/// and above: it either fails to compile or (for the prefix-parity check)
+/// silently truncates a wide prefix before reading its low bit. This
+/// trait instead mirrors the handful of operations bignum crates like
+/// `bnum`/`awint` already expose for arbitrary-width integers, so the
+/// same functions work unmodified for `u128` and, behind the `bnum`
+/// feature, for `bnum::BUint<N>`.
+pub trait WideUint: Sized + Copy + PartialOrd + Eq {
+    /// Width of the representation in bits. Uses each backend's own
+    /// `BITS` associated const rather than `size_of::<T>() * 8`, which
+    /// bignum backends don't size to a whole number of bytes.
+    const BITS: u32;
+
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn leading_zeros(self) -> u32;
+    fn add(self, rhs: Self) -> Self;
+    fn sub(self, rhs: Self) -> Self;
+    fn shl(self, rhs: u32) -> Self;
+    fn shr(self, rhs: u32) -> Self;
+    fn bitor(self, rhs: Self) -> Self;
+    fn bitand(self, rhs: Self) -> Self;
+    /// Low bit of `self`, used to pick a suffix scheme. Replaces
+    /// `prefix.into() & 1`, which overflows once `T` is wider than `u32`.
+    fn low_bit(self) -> u32;
+    /// Truncating conversion to `u64`, used only for quantities already
+    /// known to fit (bucket indices, dense output ordinals).
+    fn to_u64(self) -> u64;
+    /// Widening conversion from `u64`, used to bring a value already
+    /// known to fit (e.g. a chunk of RNG output) up into `T`'s domain.
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_wide_uint_for_prim {
+    ($($t:ty),*) => {
+        $(
+            impl WideUint for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn zero() -> Self {
+                    0
+                }
+
+                fn one() -> Self {
+                    1
+                }
+
+                fn leading_zeros(self) -> u32 {
+                    <$t>::leading_zeros(self)
+                }
+
+                fn add(self, rhs: Self) -> Self {
+                    self + rhs
+                }
+
+                fn sub(self, rhs: Self) -> Self {
+                    self - rhs
+                }
+
+                fn shl(self, rhs: u32) -> Self {
+                    self << rhs
+                }
+
+                fn shr(self, rhs: u32) -> Self {
+                    self >> rhs
+                }
+
+                fn bitor(self, rhs: Self) -> Self {
+                    self | rhs
+                }
+
+                fn bitand(self, rhs: Self) -> Self {
+                    self & rhs
+                }
+
+                fn low_bit(self) -> u32 {
+                    (self & 1) as u32
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_wide_uint_for_prim!(u8, u16, u32, u64, u128);
+
+/// Bridges `bnum`'s fixed-width bignums into [`WideUint`], so
+/// `compress_int` and friends work on key types wider than `u128`
+/// without any change to their own code. `awint`'s types are dynamically
+/// sized at runtime rather than fixed per-`N` at compile time, so they
+/// don't fit this trait as directly and aren't bridged here.
+#[cfg(feature = "bnum")]
+impl<const N: usize> WideUint for bnum::BUint<N> {
+    const BITS: u32 = Self::BITS;
+
+    fn zero() -> Self {
+        Self::ZERO
+    }
+
+    fn one() -> Self {
+        Self::ONE
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        self + rhs
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        self - rhs
+    }
+
+    fn shl(self, rhs: u32) -> Self {
+        self << rhs
+    }
+
+    fn shr(self, rhs: u32) -> Self {
+        self >> rhs
+    }
+
+    fn bitor(self, rhs: Self) -> Self {
+        self | rhs
+    }
+
+    fn bitand(self, rhs: Self) -> Self {
+        self & rhs
+    }
+
+    fn low_bit(self) -> u32 {
+        (self.digits()[0] & 1) as u32
+    }
+
+    fn to_u64(self) -> u64 {
+        self.digits()[0]
+    }
+
+    fn from_u64(value: u64) -> Self {
+        Self::from(value)
+    }
+}
+
+/// Suffix strategy for [`compress_int_with`]: which value to pick, out of
+/// the span of inputs sharing a compressed prefix, as the compressed
+/// output. Analogous to the explicit rounding modes float codecs carry.
+pub enum RoundingMode<'a> {
+    /// Suffix is all zero. Fastest, but biased low: every compressed
+    /// output is `<=` its input.
+    Truncate,
+    /// Suffix rounds the dropped bits to the nearest representable
+    /// value (`+= 1 << (shift - 1)`, then truncate), breaking exact
+    /// ties toward an even prefix.
+    NearestEven,
+    /// Deterministically alternates between two suffix schemes based on
+    /// prefix parity, cancelling mean bias over a sequence of inputs.
+    /// This is the original `compress_int` behavior and the default.
+    AlternatingScheme,
+    /// Rounds the prefix up with probability equal to the dropped bits
+    /// divided by `2^shift`, using the supplied RNG. This makes the
+    /// compressed value an unbiased estimator of the input per-sample,
+    /// rather than only in aggregate like `AlternatingScheme`, which
+    /// matters for streaming counters whose inputs aren't uniform.
+    Stochastic(&'a mut dyn RngCore),
+}
 
 /// Compress integer input so that higher resolution is preserved for
 /// values near zero, while reducing systematic bias between input and
@@ -35,44 +218,730 @@ use std::ops::{Shl, Shr};
 ///
 /// T must be of an unsigned integral type
 ///
-pub fn compress_int<T>(input: T, bit_count: u32) -> T
-where
-    T: Integer + PrimInt + Unsigned + Shl<u32, Output = T> + Shr<u32, Output = T> + Into<u32>,
-{
+/// Delegates to [`compress_int_with`] using [`RoundingMode::AlternatingScheme`],
+/// kept as the default for backward compatibility.
+pub fn compress_int<T: WideUint>(input: T, bit_count: u32) -> T {
+    compress_int_with(input, bit_count, RoundingMode::AlternatingScheme)
+}
+
+/// Like [`compress_int`], but with the suffix/rounding strategy made
+/// explicit via `mode` instead of hardcoding `AlternatingScheme`.
+pub fn compress_int_with<T: WideUint>(input: T, bit_count: u32, mode: RoundingMode) -> T {
     assert!(bit_count > 0);
 
     // find last bit (should match POSIX fls() function)
-    let input_bit_count = (size_of::<T>() * 8) as u32;
-    let high_bit_index = input_bit_count - input.leading_zeros();
+    let high_bit_index = T::BITS - input.leading_zeros();
 
     if high_bit_index <= bit_count {
         return input;
     }
 
     let shift = high_bit_index - bit_count;
-    let prefix = input >> shift;
+    let prefix = input.shr(shift);
 
-    // switch off between two different suffix schemes to reduce bias
-    // scheme 1: suffix is 0b10000...
-    // scheme 2: suffix is 0b01111...
-    let mut suffix = T::one() << (shift - 1);
+    match mode {
+        RoundingMode::Truncate => prefix.shl(shift),
 
-    if ((if bit_count == 1 {
-        shift
-    } else {
-        prefix.clone().into()
-    }) & 0b1) == 1
+        RoundingMode::NearestEven => round_nearest_even(input, prefix, shift),
+
+        RoundingMode::AlternatingScheme => {
+            // switch off between two different suffix schemes to reduce bias
+            // scheme 1: suffix is 0b10000...
+            // scheme 2: suffix is 0b01111...
+            let suffix = suffix_for(prefix, shift, bit_count);
+            prefix.shl(shift).bitor(suffix)
+        }
+
+        RoundingMode::Stochastic(rng) => stochastic_round(input, prefix, shift, rng),
+    }
+}
+
+fn round_nearest_even<T: WideUint>(input: T, prefix: T, shift: u32) -> T {
+    let dropped_bits = input.bitand(T::one().shl(shift).sub(T::one()));
+    let half = T::one().shl(shift - 1);
+
+    let rounded_prefix = if dropped_bits > half || (dropped_bits == half && prefix.low_bit() == 1)
     {
-        suffix = suffix - T::one();
+        prefix.add(T::one())
+    } else {
+        prefix
+    };
+
+    rounded_prefix.shl(shift)
+}
+
+fn stochastic_round<T: WideUint>(input: T, prefix: T, shift: u32, rng: &mut dyn RngCore) -> T {
+    let dropped_bits = input.bitand(T::one().shl(shift).sub(T::one()));
+    let threshold = random_below_shl::<T>(rng, shift);
+
+    let rounded_prefix = if threshold < dropped_bits {
+        prefix.add(T::one())
+    } else {
+        prefix
+    };
+
+    rounded_prefix.shl(shift)
+}
+
+/// Uniform random value in `[0, 2^shift)`, built up from 64-bit chunks of
+/// RNG output so `shift` can exceed 64 (as it routinely does once `T` is
+/// `u128` or a bignum). Doing this in `T`'s own domain, rather than
+/// computing `1u64 << shift` directly, is what keeps [`stochastic_round`]
+/// from overflowing a `u64` shift once `T::BITS - bit_count >= 64`.
+fn random_below_shl<T: WideUint>(rng: &mut dyn RngCore, shift: u32) -> T {
+    let mut result = T::zero();
+    let mut remaining = shift;
+
+    while remaining > 0 {
+        let chunk = remaining.min(64);
+        let bits = if chunk == 64 {
+            rng.next_u64()
+        } else {
+            rng.gen_range(0..(1u64 << chunk))
+        };
+
+        result = result.shl(chunk).bitor(T::from_u64(bits));
+        remaining -= chunk;
+    }
+
+    result
+}
+
+/// Bridges a signed integer type to the unsigned [`WideUint`] type that
+/// `compress_int` operates on, so `compress_signed` can delegate to it.
+pub trait Signed: Sized + Copy + PartialOrd {
+    type Unsigned: WideUint;
+
+    /// Splits `self` into `(is_negative, magnitude)`, the way
+    /// float-to-int conversion builtins extract a sign bit before
+    /// operating on the magnitude. `Self::MIN` has no positive
+    /// counterpart in two's complement, so its magnitude is computed
+    /// directly in the unsigned domain rather than by negating `self`.
+    fn extract_sign(self) -> (bool, Self::Unsigned);
+
+    /// Reassembles a signed value from a sign bit and a magnitude
+    /// already in the unsigned domain.
+    fn reattach_sign(is_negative: bool, magnitude: Self::Unsigned) -> Self;
+}
+
+macro_rules! impl_signed {
+    ($($signed:ty => $unsigned:ty),*) => {
+        $(
+            impl Signed for $signed {
+                type Unsigned = $unsigned;
+
+                fn extract_sign(self) -> (bool, Self::Unsigned) {
+                    (self < 0, self.unsigned_abs())
+                }
+
+                fn reattach_sign(is_negative: bool, magnitude: Self::Unsigned) -> Self {
+                    // negate in the unsigned domain before reinterpreting as
+                    // signed, so this matches two's complement bit-for-bit
+                    // (including the `Self::MIN` case) instead of negating
+                    // an already-cast value, which would panic on overflow
+                    let bits = if is_negative {
+                        (0 as $unsigned).wrapping_sub(magnitude)
+                    } else {
+                        magnitude
+                    };
+                    bits as $signed
+                }
+            }
+        )*
+    };
+}
+
+impl_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+/// Compress a signed integer the same way `compress_int` compresses an
+/// unsigned one, preserving resolution near zero symmetrically on both
+/// sides, for count-like data such as deltas or centered histograms.
+///
+/// The sign is split off first (`extract_sign`), `compress_int` runs on
+/// the magnitude alone at `bit_count`, and the sign is reattached
+/// afterwards by negating in the unsigned domain and reinterpreting the
+/// bits as signed, the same way two's complement negation always works.
+/// Because `compress_int` never rounds a nonzero magnitude down to
+/// zero, reattaching a sign can never turn a compressed zero into `-0`.
+///
+/// `T::MIN`'s magnitude sits exactly at the unsigned domain's halfway
+/// point, one more than `T::MAX`. For every `bit_count` below the width
+/// of `T`, `compress_int` rounds that magnitude up, and reattaching the
+/// sign wraps in the unsigned domain, so the result is positive (for
+/// example `compress_signed(i32::MIN, 31) == i32::MAX`). At a
+/// `bit_count` of the full width or more, `T::MIN` is returned unchanged.
+pub fn compress_signed<T>(input: T, bit_count: u32) -> T
+where
+    T: Signed,
+{
+    let (is_negative, magnitude) = input.extract_sign();
+    let compressed = compress_int(magnitude, bit_count);
+    T::reattach_sign(is_negative, compressed)
+}
+
+/// Inclusive `[min, max]` range of inputs that `compress_int` maps to
+/// `output` at the given `bit_count`.
+///
+/// This lets code building a histogram from compressed keys recover the
+/// span each bucket actually covers. It works backwards through the same
+/// compression math: `output`'s leading bit sits at the same position as
+/// the original input's did (compression never touches bits above the
+/// suffix), so recomputing `high_bit_index` and `shift` from `output`
+/// recovers the `prefix` that every input in the bucket shares. Every
+/// combination of that prefix with the `shift` low bits is a member of
+/// the bucket, regardless of which suffix scheme actually produced
+/// `output`, so the bounds are simply the all-zero and all-one suffixes.
+pub fn decompress_bounds<T: WideUint>(output: T, bit_count: u32) -> (T, T) {
+    assert!(bit_count > 0);
+
+    let high_bit_index = T::BITS - output.leading_zeros();
+
+    if high_bit_index <= bit_count {
+        return (output, output);
+    }
+
+    let shift = high_bit_index - bit_count;
+    let prefix = output.shr(shift);
+    let min = prefix.shl(shift);
+    let max = min.bitor(T::one().shl(shift).sub(T::one()));
+
+    (min, max)
+}
+
+/// Maps `compress_int(input, bit_count)` to a dense index starting at 0,
+/// suitable for indexing a `Vec`-based bucket table instead of a sparse
+/// map keyed on the compressed value itself.
+///
+/// The `2^bit_count` inputs below the compression threshold are their
+/// own output and get the first `2^bit_count` indices unchanged. Above
+/// the threshold, every increment of `shift` (one more bit of resolution
+/// traded away) contributes another `2^(bit_count - 1)` distinct
+/// prefixes, so the index is the size of every earlier group plus this
+/// input's position within its own group.
+///
+/// The dense index itself is a `u64`, so `bit_count` is capped at 63
+/// regardless of how wide `T` is: a `bit_count` above that would make
+/// `2^bit_count` itself overflow `u64` before a single bucket could even
+/// be numbered.
+pub fn bucket_index<T: WideUint>(input: T, bit_count: u32) -> u64 {
+    assert!(bit_count > 0);
+    assert!(
+        bit_count <= 63,
+        "bucket_index's dense index is a u64; bit_count must be <= 63"
+    );
+
+    let high_bit_index = T::BITS - input.leading_zeros();
+
+    if high_bit_index <= bit_count {
+        return input.to_u64();
+    }
+
+    let shift = high_bit_index - bit_count;
+    // computed in u128, not u64: groups_before scales with T::BITS, which
+    // for a wide bignum backend can make `groups_before * half` overflow
+    // a u64 well within the bit_count <= 63 bound asserted above
+    let prefix = input.shr(shift).to_u64() as u128;
+    let half = 1u128 << (bit_count - 1);
+    let identity_limit = 1u128 << bit_count;
+    let groups_before = (shift - 1) as u128;
+
+    let index = identity_limit + groups_before * half + (prefix - half);
+
+    u64::try_from(index)
+        .expect("bucket_index: too many distinct buckets for this bit_count/T to fit a u64 index")
+}
+
+fn suffix_for<T: WideUint>(prefix: T, shift: u32, bit_count: u32) -> T {
+    let mut suffix = T::one().shl(shift - 1);
+
+    let parity = if bit_count == 1 { shift } else { prefix.low_bit() };
+
+    if (parity & 0b1) == 1 {
+        suffix = suffix.sub(T::one());
+    }
+
+    suffix
+}
+
+/// Same computation as [`suffix_for`], but done in plain `u64` for
+/// [`write_prefix_and_scheme`]/[`read_prefix_and_scheme`], which work in
+/// that domain directly rather than going through `WideUint`.
+fn suffix_for_u64(prefix: u64, shift: u32, bit_count: u32) -> u64 {
+    let mut suffix = 1u64 << (shift - 1);
+
+    let parity = if bit_count == 1 { shift as u64 } else { prefix & 1 };
+
+    if (parity & 1) == 1 {
+        suffix -= 1;
+    }
+
+    suffix
+}
+
+/// Iterator over every distinct value `compress_int` can produce at a
+/// given `bit_count`, in increasing order, up to (and including)
+/// `upper_bound`. Returned by [`representable_outputs`].
+pub struct RepresentableOutputs<T> {
+    bit_count: u32,
+    max_shift: u32,
+    upper_bound: T,
+    half: T,
+    identity_limit: T,
+    // `bit_count >= T::BITS` means compress_int never compresses anything
+    // for this T (every value has its own high bit at or below bit_count),
+    // so the identity phase never transitions to a shifted one. Needed
+    // because `identity_limit` itself (2^bit_count) can't be represented
+    // in T in that case.
+    identity_only: bool,
+    cursor: T,
+    shift: u32,
+    prefix: T,
+    done: bool,
+}
+
+impl<T: WideUint> Iterator for RepresentableOutputs<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+
+        let value = if self.shift == 0 {
+            self.cursor
+        } else {
+            let suffix = suffix_for(self.prefix, self.shift, self.bit_count);
+            self.prefix.shl(self.shift).bitor(suffix)
+        };
+
+        if value > self.upper_bound {
+            self.done = true;
+            return None;
+        }
+
+        // stop now rather than advancing past upper_bound, which could
+        // overflow self.cursor/self.prefix if upper_bound is T::MAX
+        if value == self.upper_bound {
+            self.done = true;
+            return Some(value);
+        }
+
+        if self.shift == 0 {
+            self.cursor = self.cursor.add(T::one());
+            if !self.identity_only && self.cursor == self.identity_limit {
+                self.shift = 1;
+                self.prefix = self.half;
+            }
+        } else {
+            self.prefix = self.prefix.add(T::one());
+            if self.prefix == self.half.add(self.half) {
+                self.shift += 1;
+                self.prefix = self.half;
+                if self.shift > self.max_shift {
+                    self.done = true;
+                }
+            }
+        }
+
+        Some(value)
+    }
+}
+
+/// Builds the [`RepresentableOutputs`] iterator for `bit_count`, bounded
+/// above by `upper_bound`.
+pub fn representable_outputs<T: WideUint>(bit_count: u32, upper_bound: T) -> RepresentableOutputs<T> {
+    assert!(bit_count > 0);
+
+    // mirrors compress_int/decompress_bounds/bucket_index: bit_count >= T::BITS
+    // means nothing is ever compressed, and 2^bit_count itself wouldn't fit in T
+    let identity_only = bit_count >= T::BITS;
+    let half = if identity_only {
+        T::zero()
+    } else {
+        T::one().shl(bit_count - 1)
+    };
+
+    RepresentableOutputs {
+        bit_count,
+        max_shift: T::BITS - bit_count.min(T::BITS),
+        upper_bound,
+        half,
+        identity_limit: if identity_only {
+            T::zero()
+        } else {
+            T::one().shl(bit_count)
+        },
+        identity_only,
+        cursor: T::zero(),
+        shift: 0,
+        prefix: half,
+        done: false,
+    }
+}
+
+/// Number of values grouped into a single bitpacked block by
+/// [`CompressedIntWriter`].
+///
+/// Each block carries its own bit width, so a run of small values packs
+/// tighter than a run of large ones, at the cost of one header byte per
+/// `BLOCK_SIZE` values.
+const BLOCK_SIZE: usize = 128;
+
+/// Appends `bits`-wide little-endian bit fields to `out`, least
+/// significant bit first, starting on a fresh byte after whatever is
+/// already in `out` and zero-padding its own last partial byte in
+/// `finish`.
+struct BitWriter<'a> {
+    out: &'a mut Vec<u8>,
+    cur_byte: u8,
+    cur_bits: u32,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(out: &'a mut Vec<u8>) -> Self {
+        BitWriter {
+            out,
+            cur_byte: 0,
+            cur_bits: 0,
+        }
+    }
+
+    fn write(&mut self, mut value: u64, mut bits: u32) {
+        while bits > 0 {
+            let space = 8 - self.cur_bits;
+            let take = space.min(bits);
+            let mask = (1u64 << take) - 1;
+            self.cur_byte |= ((value & mask) as u8) << self.cur_bits;
+            self.cur_bits += take;
+            value >>= take;
+            bits -= take;
+
+            if self.cur_bits == 8 {
+                self.out.push(self.cur_byte);
+                self.cur_byte = 0;
+                self.cur_bits = 0;
+            }
+        }
+    }
+
+    fn finish(self) {
+        if self.cur_bits > 0 {
+            self.out.push(self.cur_byte);
+        }
+    }
+}
+
+/// Reads `bits`-wide little-endian fields back out of a byte slice
+/// written by [`BitWriter`].
+fn read_bits(data: &[u8], field_index: usize, bits: u32) -> u64 {
+    if bits == 0 {
+        return 0;
+    }
+
+    let start_bit = field_index as u64 * bits as u64;
+    let mut value: u64 = 0;
+    let mut written = 0u32;
+
+    while written < bits {
+        let bit_pos = start_bit + written as u64;
+        let byte_index = (bit_pos / 8) as usize;
+        let bit_offset = (bit_pos % 8) as u32;
+        let available = 8 - bit_offset;
+        let take = available.min(bits - written);
+        let mask = (1u64 << take) - 1;
+        let bits_here = ((data[byte_index] as u64) >> bit_offset) & mask;
+
+        value |= bits_here << written;
+        written += take;
     }
 
-    (prefix << shift) | suffix
+    value
+}
+
+fn bytes_for(count: usize, bits: u32) -> usize {
+    (count as u64 * bits as u64).div_ceil(8) as usize
 }
 
+/// Streaming writer that packs a sequence of `compress_int` outputs into
+/// a dense, bitpacked byte buffer, the way tantivy's bitpacker stores a
+/// column of integers.
+///
+/// Values are buffered in blocks of [`BLOCK_SIZE`]. When a block fills
+/// (or [`finish`](CompressedIntWriter::finish) is called on a partial
+/// one), the minimal bit width needed to hold the block's largest value
+/// is computed as `bits = T::BITS - max.leading_zeros()` and written as
+/// a single header byte, followed by every value in the block packed
+/// into that many bits. An all-zero block is given width 0 and takes up
+/// only its header byte.
+///
+/// The reader needs to be told the total value count separately, since
+/// it is not stored in the buffer.
+///
+/// Values are packed through a `u64` field internally, so `T` is limited
+/// to 64 bits or fewer; [`new`](CompressedIntWriter::new) panics for
+/// wider `T` (`u128`, or a `bnum` backend) rather than silently
+/// truncating or overflowing partway through a block.
+pub struct CompressedIntWriter<T> {
+    pending: Vec<T>,
+    out: Vec<u8>,
+}
+
+impl<T> Default for CompressedIntWriter<T>
+where
+    T: PrimInt + Unsigned,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> CompressedIntWriter<T>
+where
+    T: PrimInt + Unsigned,
+{
+    pub fn new() -> Self {
+        assert!(
+            size_of::<T>() * 8 <= 64,
+            "CompressedIntWriter packs values through a u64 field; T must be 64 bits or fewer"
+        );
+
+        CompressedIntWriter {
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            out: Vec::new(),
+        }
+    }
+
+    /// Buffer a single value, flushing a full block if this fills one.
+    pub fn push(&mut self, value: T) {
+        self.pending.push(value);
+        if self.pending.len() == BLOCK_SIZE {
+            self.flush_block();
+        }
+    }
+
+    /// Buffer every value in `values`.
+    pub fn extend(&mut self, values: &[T]) {
+        for &value in values {
+            self.push(value);
+        }
+    }
+
+    fn flush_block(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let input_bit_count = (size_of::<T>() * 8) as u32;
+        let max = self.pending.iter().fold(T::zero(), |acc, &v| acc.max(v));
+        let bits = input_bit_count - max.leading_zeros();
+
+        self.out.push(bits as u8);
+
+        let mut writer = BitWriter::new(&mut self.out);
+        for &value in &self.pending {
+            writer.write(value.to_u64().unwrap(), bits);
+        }
+        writer.finish();
+
+        self.pending.clear();
+    }
+
+    /// Flush any partially-filled trailing block and return the packed
+    /// buffer.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.flush_block();
+        self.out
+    }
+}
+
+/// Random-access reader over a buffer produced by
+/// [`CompressedIntWriter`].
+pub struct CompressedIntReader<T> {
+    // byte offset of each block's packed region, paired with that
+    // block's bit width
+    blocks: Vec<(usize, u32)>,
+    data: Vec<u8>,
+    len: usize,
+    _marker: ::std::marker::PhantomData<T>,
+}
+
+impl<T> CompressedIntReader<T>
+where
+    T: PrimInt + Unsigned,
+{
+    /// Build a reader over `data`, which must hold exactly `len` values
+    /// as written by [`CompressedIntWriter`].
+    pub fn new(data: &[u8], len: usize) -> Self {
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        let mut remaining = len;
+
+        while remaining > 0 {
+            let bits = data[offset] as u32;
+            offset += 1;
+
+            let count = remaining.min(BLOCK_SIZE);
+            blocks.push((offset, bits));
+
+            offset += bytes_for(count, bits);
+            remaining -= count;
+        }
+
+        CompressedIntReader {
+            blocks,
+            data: data.to_vec(),
+            len,
+            _marker: ::std::marker::PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> T {
+        assert!(index < self.len);
+
+        let (byte_offset, bits) = self.blocks[index / BLOCK_SIZE];
+        let field = read_bits(&self.data[byte_offset..], index % BLOCK_SIZE, bits);
+
+        T::from(field).unwrap()
+    }
+
+    /// Iterate over every value in order.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = T> + 'a {
+        (0..self.len).map(move |i| self.get(i))
+    }
+}
+
+/// Number of bits needed to store any `shift` that
+/// [`write_prefix_and_scheme`] can derive for a `T` that is
+/// `input_bit_count` bits wide (the largest possible shift is
+/// `input_bit_count - 1`, when `bit_count == 1`).
+fn prefix_and_scheme_shift_bits(input_bit_count: u32) -> u32 {
+    let max_shift = input_bit_count.saturating_sub(1);
+    32 - max_shift.leading_zeros()
+}
+
+/// Per-value field size for the compact prefix+scheme packing described
+/// in [`write_prefix_and_scheme`]: the value's own shift (so each value
+/// is self-describing rather than assuming a single shift for the whole
+/// slice), its `bit_count` prefix bits, and one bit identifying whether
+/// `compress_int`'s alternating suffix scheme produced a nonzero suffix.
+/// The field is narrower than a full `T` only while that sum is below
+/// `T`'s width (9 bits for `u32` at `bit_count` 3); it is not
+/// necessarily narrower than the per-block width [`CompressedIntWriter`]
+/// packs the same values at.
+fn prefix_and_scheme_field_bits(input_bit_count: u32, bit_count: u32) -> u32 {
+    prefix_and_scheme_shift_bits(input_bit_count) + bit_count + 1
+}
+
+/// Packs `values` into a buffer of, per value: its own shift, its
+/// `bit_count` prefix bits, and one scheme bit (see
+/// [`prefix_and_scheme_field_bits`] for how that width compares with
+/// storing the full value).
+///
+/// Unlike a single shift derived from the whole slice (or even from a
+/// block of it), storing each value's own shift — `high_bit_index -
+/// bit_count`, the same quantity [`compress_int`] itself would have used
+/// — means this round-trips a slice of heterogeneous magnitudes, such as
+/// `(0..300).map(|i| compress_int(i, bit_count))`, rather than silently
+/// misreading values that don't share the majority's high-bit position.
+///
+/// Per value, the scheme bit records whether `compress_int`'s
+/// `AlternatingScheme` suffix (re-derived via [`suffix_for_u64`] from the
+/// prefix) was used, or whether the suffix is all zero, which is what
+/// every other [`RoundingMode`] (`Truncate`, `NearestEven`, `Stochastic`)
+/// always produces — they only ever adjust the prefix, never inject
+/// suffix bits — so this one bit is enough to round-trip any of them.
+///
+/// This round-trips any value `compress_int_with` returns at `bit_count`,
+/// including inputs with `bit_count` or fewer significant bits, which
+/// are stored with a shift of 0. A value whose nonzero bits below the
+/// prefix differ from the `AlternatingScheme` suffix reads back with
+/// that suffix instead.
+pub fn write_prefix_and_scheme<T>(values: &[T], bit_count: u32, out: &mut Vec<u8>)
+where
+    T: PrimInt + Unsigned,
+{
+    assert!(bit_count > 0);
+    assert!(
+        size_of::<T>() * 8 <= 64,
+        "write_prefix_and_scheme packs values through a u64 field; T must be 64 bits or fewer"
+    );
+
+    let input_bit_count = (size_of::<T>() * 8) as u32;
+    let shift_bits = prefix_and_scheme_shift_bits(input_bit_count);
+    let field_bits = shift_bits + bit_count + 1;
+    assert!(
+        field_bits <= 64,
+        "write_prefix_and_scheme packs each value's shift, prefix and scheme bit through a \
+         u64 field; bit_count is too large relative to T for this to fit"
+    );
+
+    let mut writer = BitWriter::new(out);
+    for &value in values {
+        let v = value.to_u64().unwrap();
+        let high_bit_index = input_bit_count - value.leading_zeros();
+        let shift = high_bit_index.saturating_sub(bit_count);
+
+        let prefix = v >> shift;
+        let dropped = if shift == 0 { 0 } else { v & ((1u64 << shift) - 1) };
+        let scheme_bit = u64::from(dropped != 0);
+
+        let field = ((shift as u64) << (bit_count + 1)) | (prefix << 1) | scheme_bit;
+        writer.write(field, field_bits);
+    }
+    writer.finish();
+}
+
+/// Reverses [`write_prefix_and_scheme`] for the value at `index` within
+/// a buffer of `len` values packed at `bit_count`.
+pub fn read_prefix_and_scheme<T>(data: &[u8], index: usize, len: usize, bit_count: u32) -> T
+where
+    T: PrimInt + Unsigned,
+{
+    assert!(index < len);
+    assert!(
+        size_of::<T>() * 8 <= 64,
+        "read_prefix_and_scheme packs values through a u64 field; T must be 64 bits or fewer"
+    );
+
+    let input_bit_count = (size_of::<T>() * 8) as u32;
+    let field_bits = prefix_and_scheme_field_bits(input_bit_count, bit_count);
+    let field = read_bits(data, index, field_bits);
+
+    let scheme_bit = field & 1;
+    let prefix = (field >> 1) & ((1u64 << bit_count) - 1);
+    let shift = (field >> (bit_count + 1)) as u32;
+
+    let value = if shift == 0 {
+        prefix
+    } else {
+        let suffix = if scheme_bit == 1 {
+            suffix_for_u64(prefix, shift, bit_count)
+        } else {
+            0
+        };
+        (prefix << shift) | suffix
+    };
+
+    T::from(value).unwrap()
+}
 
 #[cfg(test)]
 mod tests {
     use compress_int;
+    use {
+        bucket_index, compress_int_with, compress_signed, decompress_bounds,
+        read_prefix_and_scheme, representable_outputs, write_prefix_and_scheme, CompressedIntReader,
+        CompressedIntWriter, RoundingMode, BLOCK_SIZE,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_compression() {
@@ -113,4 +982,434 @@ mod tests {
 
         assert_relative_eq!(sum, expect, epsilon = eps);
     }
+
+    #[test]
+    fn test_compression_u128_matches_u32() {
+        let bit_count: u32 = 3;
+        for i in 0u128..8u128 {
+            assert_eq!(compress_int(i, bit_count), i);
+        }
+        for i in 8u128..10u128 {
+            assert_eq!(compress_int(i, bit_count), 9);
+        }
+        for i in 10u128..12u128 {
+            assert_eq!(compress_int(i, bit_count), 10);
+        }
+        for i in 16u128..20u128 {
+            assert_eq!(compress_int(i, bit_count), 18);
+        }
+
+        assert_eq!(compress_int(67u128, bit_count), 72u128);
+
+        // a 128-bit-only value, well beyond u32's range, still compresses
+        let big: u128 = 1 << 100;
+        assert_eq!(compress_int(big, bit_count), (1u128 << 100) + (1u128 << 97));
+    }
+
+    #[test]
+    fn test_decompress_bounds_u128() {
+        assert_eq!(decompress_bounds(72u128, 3), (64u128, 79u128));
+    }
+
+    #[test]
+    fn test_compress_signed_mirrors_unsigned() {
+        let bit_count: u32 = 3;
+
+        for i in 0i32..300i32 {
+            let expect = compress_int(i as u32, bit_count) as i32;
+            assert_eq!(compress_signed(i, bit_count), expect);
+            assert_eq!(compress_signed(-i, bit_count), -expect);
+        }
+    }
+
+    #[test]
+    fn test_compress_signed_never_produces_negative_zero() {
+        let bit_count: u32 = 3;
+        assert_eq!(compress_signed(0i32, bit_count), 0);
+        assert_eq!(compress_signed(-0i32, bit_count), 0);
+    }
+
+    #[test]
+    fn test_compress_signed_handles_int_min() {
+        // i32::MIN's magnitude (2^31) doesn't fit in i32; compress_signed
+        // must not panic, and must match manually wrapping the compressed
+        // magnitude the same way `i32::MIN.wrapping_neg()` does on its own
+        for bit_count in [4u32, 16, 30, 31, 32] {
+            let compressed = compress_signed(i32::MIN, bit_count);
+            let magnitude = compress_int((i32::MIN).unsigned_abs(), bit_count);
+            assert_eq!(compressed, 0u32.wrapping_sub(magnitude) as i32);
+        }
+    }
+
+    #[test]
+    fn test_compress_int_delegates_to_alternating_scheme() {
+        let bit_count: u32 = 3;
+        for i in 0u32..300u32 {
+            assert_eq!(
+                compress_int(i, bit_count),
+                compress_int_with(i, bit_count, RoundingMode::AlternatingScheme)
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncate_is_biased_low() {
+        let bit_count: u32 = 3;
+        for i in 0u32..300u32 {
+            let compressed = compress_int_with(i, bit_count, RoundingMode::Truncate);
+            assert!(compressed <= i);
+            // matches compress_int's own prefix, just with an all-zero suffix
+            let (min, _) = decompress_bounds(compress_int(i, bit_count), bit_count);
+            assert_eq!(compressed, min);
+        }
+    }
+
+    #[test]
+    fn test_nearest_even_rounds_to_closer_candidate() {
+        let bit_count: u32 = 3;
+        for i in 0u32..300u32 {
+            let compressed = compress_int_with(i, bit_count, RoundingMode::NearestEven);
+
+            let high_bit_index = 32 - i.leading_zeros();
+            if high_bit_index <= bit_count {
+                assert_eq!(compressed, i);
+                continue;
+            }
+
+            // the two multiples of 2^shift that i falls between
+            let shift = high_bit_index - bit_count;
+            let low = (i >> shift) << shift;
+            let high = low + (1 << shift);
+            let half = 1 << (shift - 1);
+            let remainder = i - low;
+
+            let expected = match remainder.cmp(&half) {
+                std::cmp::Ordering::Less => low,
+                std::cmp::Ordering::Greater => high,
+                // tie: round toward whichever candidate has an even prefix
+                std::cmp::Ordering::Equal if (low >> shift).is_multiple_of(2) => low,
+                std::cmp::Ordering::Equal => high,
+            };
+
+            assert_eq!(compressed, expected);
+        }
+    }
+
+    #[test]
+    fn test_nearest_even_breaks_ties_toward_even_prefix() {
+        // bit_count=2, input=14 (0b1110): shift=2, the dropped bits (0b10)
+        // are exactly half of the span (a tie), and the low prefix 0b11=3
+        // is odd, so this must round up to the even prefix 0b100=4,
+        // giving 16 rather than 12.
+        let compressed = compress_int_with(14u32, 2, RoundingMode::NearestEven);
+        assert_eq!(compressed, 16);
+
+        // input=10 (0b1010): same shift, same tie, but the low prefix
+        // 0b10=2 is already even, so this rounds down to 8.
+        let compressed = compress_int_with(10u32, 2, RoundingMode::NearestEven);
+        assert_eq!(compressed, 8);
+    }
+
+    #[test]
+    fn test_stochastic_is_unbiased_per_input() {
+        let bit_count: u32 = 2;
+        let input = 23u32; // not exactly representable at bit_count=2
+        let trials = 20_000;
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let mut sum = 0f64;
+        for _ in 0..trials {
+            sum += compress_int_with(input, bit_count, RoundingMode::Stochastic(&mut rng)) as f64;
+        }
+
+        assert_relative_eq!(sum / trials as f64, input as f64, epsilon = 0.1);
+    }
+
+    #[test]
+    fn test_stochastic_handles_shift_wider_than_64_bits() {
+        // bit_count=1 on a 128-bit input near its top bit gives a shift
+        // of 126, well past the u64 this used to be computed in.
+        let bit_count: u32 = 1;
+        let input = (1u128 << 126) | (1u128 << 125);
+        let shift = 127 - bit_count;
+        let low = compress_int_with(input, bit_count, RoundingMode::Truncate);
+        let high = low + (1u128 << shift);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let compressed = compress_int_with(input, bit_count, RoundingMode::Stochastic(&mut rng));
+            assert!(compressed == low || compressed == high);
+        }
+    }
+
+    #[test]
+    fn test_writer_reader_round_trip() {
+        let bit_count: u32 = 3;
+        let values: Vec<u32> = (0u32..300u32).map(|i| compress_int(i, bit_count)).collect();
+
+        let mut writer = CompressedIntWriter::new();
+        writer.extend(&values);
+        let packed = writer.finish();
+
+        let reader: CompressedIntReader<u32> = CompressedIntReader::new(&packed, values.len());
+        assert_eq!(reader.len(), values.len());
+
+        let round_tripped: Vec<u32> = reader.iter().collect();
+        assert_eq!(round_tripped, values);
+    }
+
+    #[test]
+    fn test_writer_reader_all_zero_block() {
+        let values = vec![0u32; BLOCK_SIZE];
+
+        let mut writer = CompressedIntWriter::new();
+        writer.extend(&values);
+        let packed = writer.finish();
+
+        // an all-zero block should collapse to a single header byte
+        assert_eq!(packed.len(), 1);
+        assert_eq!(packed[0], 0);
+
+        let reader: CompressedIntReader<u32> = CompressedIntReader::new(&packed, values.len());
+        for i in 0..values.len() {
+            assert_eq!(reader.get(i), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "T must be 64 bits or fewer")]
+    fn test_writer_rejects_types_wider_than_64_bits() {
+        // values are packed through a u64 field internally, so a value
+        // beyond u64::MAX would otherwise panic partway through a block
+        // instead of up front
+        let _writer: CompressedIntWriter<u128> = CompressedIntWriter::new();
+    }
+
+    #[test]
+    fn test_prefix_and_scheme_round_trip() {
+        let bit_count: u32 = 3;
+        let values: Vec<u32> = (64u32..96u32).map(|i| compress_int(i, bit_count)).collect();
+
+        let mut packed = Vec::new();
+        write_prefix_and_scheme(&values, bit_count, &mut packed);
+
+        for (i, &expected) in values.iter().enumerate() {
+            let actual: u32 = read_prefix_and_scheme(&packed, i, values.len(), bit_count);
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_prefix_and_scheme_round_trip_spans_multiple_shifts() {
+        // unlike the fixed 64..96 window above, this spans many
+        // power-of-two ranges (and more than one BLOCK_SIZE), which
+        // previously corrupted every value whose own shift didn't match
+        // the single shift derived from the whole slice's max.
+        let bit_count: u32 = 3;
+        let values: Vec<u32> = (0u32..300u32).map(|i| compress_int(i, bit_count)).collect();
+
+        let mut packed = Vec::new();
+        write_prefix_and_scheme(&values, bit_count, &mut packed);
+
+        for (i, &expected) in values.iter().enumerate() {
+            let actual: u32 = read_prefix_and_scheme(&packed, i, values.len(), bit_count);
+            assert_eq!(actual, expected, "mismatch at index {}", i);
+        }
+    }
+
+    #[test]
+    fn test_prefix_and_scheme_round_trips_non_alternating_modes() {
+        // Truncate/NearestEven/Stochastic all leave the suffix at zero;
+        // this must round-trip them correctly rather than assuming every
+        // value came from AlternatingScheme.
+        let bit_count: u32 = 3;
+        let mut rng = StdRng::seed_from_u64(11);
+        let modes_values: Vec<u32> = (60u32..70u32)
+            .flat_map(|i| {
+                vec![
+                    compress_int_with(i, bit_count, RoundingMode::Truncate),
+                    compress_int_with(i, bit_count, RoundingMode::NearestEven),
+                    compress_int_with(i, bit_count, RoundingMode::Stochastic(&mut rng)),
+                    compress_int_with(i, bit_count, RoundingMode::AlternatingScheme),
+                ]
+            })
+            .collect();
+
+        let mut packed = Vec::new();
+        write_prefix_and_scheme(&modes_values, bit_count, &mut packed);
+
+        for (i, &expected) in modes_values.iter().enumerate() {
+            let actual: u32 = read_prefix_and_scheme(&packed, i, modes_values.len(), bit_count);
+            assert_eq!(actual, expected, "mismatch at index {}", i);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "T must be 64 bits or fewer")]
+    fn test_write_prefix_and_scheme_rejects_types_wider_than_64_bits() {
+        let values = vec![1u128 << 100];
+        let mut packed = Vec::new();
+        write_prefix_and_scheme(&values, 3, &mut packed);
+    }
+
+    #[test]
+    fn test_decompress_bounds_round_trips_compress_int() {
+        let bit_count: u32 = 3;
+
+        for i in 0u32..300u32 {
+            let output = compress_int(i, bit_count);
+            let (min, max) = decompress_bounds(output, bit_count);
+            assert!(i >= min && i <= max);
+            // every input in the bounds must compress back to the same output
+            assert_eq!(compress_int(min, bit_count), output);
+            assert_eq!(compress_int(max, bit_count), output);
+        }
+    }
+
+    #[test]
+    fn test_decompress_bounds_example() {
+        // matches the bit_count=3, input=67 example from compress_int's docs
+        assert_eq!(decompress_bounds(72u32, 3), (64, 79));
+    }
+
+    #[test]
+    fn test_bucket_index_is_dense_and_consistent() {
+        let bit_count: u32 = 3;
+
+        let mut seen = std::collections::BTreeMap::new();
+        for i in 0u32..300u32 {
+            let output = compress_int(i, bit_count);
+            let index = bucket_index(i, bit_count);
+
+            if let Some(&existing) = seen.get(&index) {
+                assert_eq!(existing, output);
+            } else {
+                seen.insert(index, output);
+            }
+        }
+
+        // dense: indices used are exactly 0..seen.len()
+        let max_index = *seen.keys().max().unwrap();
+        assert_eq!(max_index as usize, seen.len() - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "bit_count must be <= 63")]
+    fn test_bucket_index_rejects_bit_count_above_63() {
+        // a bit_count this large would overflow u64 math internally
+        // (2^bit_count alone doesn't fit) long before a dense index
+        // could be computed, so this is rejected up front instead.
+        bucket_index(1u128 << 127, 100);
+    }
+
+    #[test]
+    fn test_representable_outputs_matches_bucket_index() {
+        let bit_count: u32 = 3;
+        let upper_bound = compress_int(300u32, bit_count);
+
+        let outputs: Vec<u32> = representable_outputs(bit_count, upper_bound).collect();
+
+        for (index, &output) in outputs.iter().enumerate() {
+            let (min, _) = decompress_bounds(output, bit_count);
+            assert_eq!(bucket_index(min, bit_count), index as u64);
+        }
+    }
+
+    #[test]
+    fn test_representable_outputs_handles_bit_count_at_full_width() {
+        // bit_count == T::BITS means nothing is ever compressed, so this
+        // must just enumerate every value up to upper_bound unchanged
+        // instead of panicking on a same-width shift.
+        let outputs: Vec<u8> = representable_outputs(8u32, 255u8).collect();
+        let expected: Vec<u8> = (0u8..=255u8).collect();
+        assert_eq!(outputs, expected);
+    }
+}
+
+/// Round-trips the `WideUint` impl for `bnum::BUint<N>` against the same
+/// expectations the `u128` tests pin, so the bignum backend added in
+/// [`WideUint`] is actually exercised under `--features bnum` rather than
+/// merely compiling.
+#[cfg(all(test, feature = "bnum"))]
+mod bnum_tests {
+    use bnum::BUint;
+    use {bucket_index, compress_int, compress_int_with, decompress_bounds, RoundingMode};
+
+    type B128 = BUint<2>;
+
+    #[test]
+    fn test_compress_int_matches_u128_at_128_bits() {
+        let bit_count: u32 = 3;
+
+        for i in 0u128..20u128 {
+            let expect = compress_int(i, bit_count);
+            assert_eq!(compress_int(B128::from(i), bit_count), B128::from(expect));
+        }
+
+        // a 128-bit-only value, well beyond u64's range
+        let big: u128 = 1 << 100;
+        let expect = compress_int(big, bit_count);
+        assert_eq!(compress_int(B128::from(big), bit_count), B128::from(expect));
+
+        // matches the bit_count=3, input=67 example from compress_int's docs
+        assert_eq!(compress_int(B128::from(67u128), bit_count), B128::from(72u128));
+    }
+
+    #[test]
+    fn test_decompress_bounds_matches_u128_at_128_bits() {
+        assert_eq!(
+            decompress_bounds(B128::from(72u128), 3),
+            (B128::from(64u128), B128::from(79u128))
+        );
+    }
+
+    #[test]
+    fn test_bucket_index_matches_u128_at_128_bits() {
+        let bit_count: u32 = 3;
+
+        for i in 0u128..300u128 {
+            let expect = bucket_index(i, bit_count);
+            assert_eq!(bucket_index(B128::from(i), bit_count), expect);
+        }
+    }
+
+    #[test]
+    fn test_stochastic_handles_shift_wider_than_64_bits() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let input = B128::from((1u128 << 126) | (1u128 << 125));
+        let compressed = compress_int_with(input, 1, RoundingMode::Stochastic(&mut rng));
+
+        let low = compress_int_with(input, 1, RoundingMode::Truncate);
+        let high = low + (B128::from(1u128) << 126u32);
+        assert!(compressed == low || compressed == high);
+    }
+
+    #[test]
+    #[should_panic(expected = "bucket_index: too many distinct buckets")]
+    fn test_bucket_index_reports_overflow_cleanly_for_wide_t() {
+        // BUint<4> is 256 bits wide, and at this shift the real dense
+        // index is bigger than u64::MAX regardless of how it's computed
+        // internally (it's not just an artifact of doing the math in
+        // u64). The fix is that this now fails with a clear, dedicated
+        // message instead of panicking mid-multiply on an unrelated u64.
+        type B256 = BUint<4>;
+        let input: B256 = B256::from(1u128) << 255u32;
+        bucket_index(input, 63);
+    }
+
+    #[test]
+    fn test_bucket_index_matches_u128_for_wide_t_when_it_fits() {
+        // a T much wider than u64 but an input/bit_count combination
+        // whose real dense index still fits comfortably in u64 must not
+        // be rejected just because T::BITS is large.
+        type B256 = BUint<4>;
+        let bit_count: u32 = 3;
+
+        for i in 0u128..300u128 {
+            let expect = bucket_index(i, bit_count);
+            assert_eq!(bucket_index(B256::from(i), bit_count), expect);
+        }
+    }
 }